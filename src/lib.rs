@@ -14,6 +14,15 @@
 //! robotstxt = "0.1"
 //! ```
 //!
+//! The pure parser above has no dependency on an HTTP client. Enable the
+//! `http` feature to also get [`RobotFileParser::fetch`], which downloads
+//! and (re-)parses a robots.txt file over the network:
+//!
+//! ```toml
+//! [dependencies]
+//! robotstxt = { version = "0.1", features = ["http"] }
+//! ```
+//!
 //! # Examples
 //!
 //! ```rust
@@ -32,14 +41,99 @@
 
 use std::borrow::Cow;
 use std::time::{Duration};
+#[cfg(feature = "http")]
+use std::time::SystemTime;
 
 use url::Url;
 
+/// A path pattern compiled from an Allow/Disallow value, supporting the
+/// de-facto `*` wildcard and `$` end-anchor extensions.
+///
+/// The pattern is split on `*` into literal segments once, at parse time,
+/// rather than re-scanning the raw path string on every `can_fetch` call.
+#[derive(Debug, Eq, PartialEq, Clone)]
+struct Pattern {
+    /// Literal segments that must appear in order, with arbitrary text
+    /// allowed between them.
+    segments: Vec<String>,
+    /// Whether the pattern starts with a `*`, i.e. the first segment need
+    /// not match as a prefix of the path.
+    leading_wildcard: bool,
+    /// Whether the pattern ends with a `*` (before any trailing `$`), i.e.
+    /// the last segment may be followed by anything, which makes a
+    /// trailing `$` a no-op.
+    trailing_wildcard: bool,
+    /// Whether the pattern ends with an unescaped `$`, requiring the final
+    /// segment to land exactly at the end of the path.
+    anchored_end: bool,
+}
+
+impl Pattern {
+    fn compile(path: &str) -> Pattern {
+        let anchored_end = path.ends_with('$');
+        let path = if anchored_end { &path[..path.len() - 1] } else { path };
+        let leading_wildcard = path.starts_with('*');
+        let trailing_wildcard = path.ends_with('*');
+        let segments = path.split('*')
+            .filter(|segment| !segment.is_empty())
+            .map(|segment| segment.to_owned())
+            .collect();
+        Pattern { segments, leading_wildcard, trailing_wildcard, anchored_end }
+    }
+
+    /// The number of literal (non-`*`/`$`) characters this pattern matches,
+    /// used to rank rules by specificity.
+    fn effective_len(&self) -> usize {
+        self.segments.iter().map(|segment| segment.len()).sum()
+    }
+
+    fn matches(&self, path: &str) -> bool {
+        if self.segments.is_empty() {
+            // an empty pattern or a lone "*" matches everything
+            return true;
+        }
+
+        let mut segments = self.segments.iter();
+        let mut cursor = 0;
+
+        if !self.leading_wildcard {
+            let first = segments.next().unwrap();
+            if !path.starts_with(first.as_str()) {
+                return false;
+            }
+            cursor = first.len();
+        }
+
+        let anchored = self.anchored_end && !self.trailing_wildcard;
+        let remaining = segments.len();
+        for (i, segment) in segments.enumerate() {
+            // an end-anchored final segment can only match at its rightmost
+            // occurrence, the only one that may end at the end of the path
+            let found = if anchored && i + 1 == remaining {
+                path[cursor..].rfind(segment.as_str())
+            } else {
+                path[cursor..].find(segment.as_str())
+            };
+            match found {
+                Some(pos) => cursor += pos + segment.len(),
+                None => return false,
+            }
+        }
+
+        if anchored && cursor != path.len() {
+            return false;
+        }
+
+        true
+    }
+}
+
 /// A rule line is a single "Allow:" (allowance==True) or "Disallow:"
 /// (allowance==False) followed by a path."""
 #[derive(Debug, Eq, PartialEq, Clone)]
 struct RuleLine<'a> {
     path: Cow<'a, str>,
+    pattern: Pattern,
     allowance: bool,
 }
 
@@ -55,7 +149,6 @@ struct Entry<'a> {
     useragents: Vec<String>,
     rulelines: Vec<RuleLine<'a>>,
     crawl_delay: Option<Duration>,
-    sitemaps: Vec<Url>,
     req_rate: Option<RequestRate>,
 }
 
@@ -70,14 +163,16 @@ impl<'a> RuleLine<'a> {
             // an empty value means allow all
             allow = true;
         }
+        let pattern = Pattern::compile(&path);
         RuleLine {
             path: path,
+            pattern,
             allowance: allow,
         }
     }
 
     fn applies_to(&self, filename: &str) -> bool {
-        self.path == "*" || filename.starts_with(&self.path[..])
+        self.pattern.matches(filename)
     }
 }
 
@@ -88,38 +183,48 @@ impl<'a> Entry<'a> {
             useragents: vec![],
             rulelines: vec![],
             crawl_delay: None,
-            sitemaps: Vec::new(),
             req_rate: None,
         }
     }
 
-    /// check if this entry applies to the specified agent
-    fn applies_to(&self, useragent: &str) -> bool {
-        let ua = useragent.split('/').nth(0).unwrap_or("").to_lowercase();
-        let useragents = &self.useragents;
-        for agent in &*useragents {
-            if agent == "*" {
-                return true;
-            }
-            if ua.contains(agent) {
-                return true;
-            }
-        }
-        false
+    /// The length of the most specific declared user-agent token that
+    /// matches `ua` (a lowercased, version-stripped user-agent), or `None`
+    /// if none of this entry's tokens apply. The `*` token is ignored here
+    /// since it is handled separately via the default entry.
+    fn matched_agent_len(&self, ua: &str) -> Option<usize> {
+        self.useragents.iter()
+            .filter(|agent| agent.as_str() != "*" && ua.contains(agent.as_str()))
+            .map(|agent| agent.len())
+            .max()
     }
 
 
     /// Preconditions:
     /// - our agent applies to this entry
     /// - filename is URL decoded
+    ///
+    /// The rule with the longest matching path wins, regardless of the
+    /// order it was declared in; ties are resolved in favor of `Allow`.
     fn allowance(&self, filename: &str) -> bool {
-        let rulelines = &self.rulelines;
-        for line in &*rulelines {
-            if line.applies_to(filename) {
-                return line.allowance;
+        let mut best: Option<&RuleLine<'a>> = None;
+        for line in &self.rulelines {
+            if !line.applies_to(filename) {
+                continue;
             }
+            best = match best {
+                None => Some(line),
+                Some(current) => {
+                    let len = line.pattern.effective_len();
+                    let current_len = current.pattern.effective_len();
+                    if len > current_len || (len == current_len && line.allowance && !current.allowance) {
+                        Some(line)
+                    } else {
+                        Some(current)
+                    }
+                }
+            };
         }
-        true
+        best.map(|line| line.allowance).unwrap_or(true)
     }
 
     fn push_useragent(&mut self, useragent: &str) {
@@ -147,16 +252,6 @@ impl<'a> Entry<'a> {
         self.crawl_delay
     }
 
-    fn add_sitemap(&mut self, url: &str) {
-        if let Ok(url) = Url::parse(url) {
-            self.sitemaps.push(url);
-        }
-    }
-
-    fn sitemaps(&self) -> &Vec<Url> {
-        &self.sitemaps
-    }
-
     fn set_request_rate(&mut self, req_rate: RequestRate) {
         self.req_rate = Some(req_rate);
     }
@@ -164,6 +259,19 @@ impl<'a> Entry<'a> {
     fn request_rate(&self) -> Option<RequestRate> {
         self.req_rate.clone()
     }
+
+    /// Merge rulelines and directives from another group declaring the
+    /// exact same user-agent token(s) into this one, since real robots.txt
+    /// files often split one agent's rules across several blocks.
+    fn merge(&mut self, other: Entry<'a>) {
+        self.rulelines.extend(other.rulelines);
+        if other.crawl_delay.is_some() {
+            self.crawl_delay = other.crawl_delay;
+        }
+        if other.req_rate.is_some() {
+            self.req_rate = other.req_rate;
+        }
+    }
 }
 
 
@@ -180,6 +288,17 @@ pub struct RobotFileParser<'a> {
     default_entry: Entry<'a>,
     disallow_all: bool,
     allow_all: bool,
+    /// `Sitemap:` directives, which apply to the whole file regardless of
+    /// which (if any) `User-agent` block they appear under.
+    sitemaps: Vec<Url>,
+    #[cfg(feature = "http")]
+    url: Option<Url>,
+    #[cfg(feature = "http")]
+    last_checked: Option<SystemTime>,
+    #[cfg(feature = "http")]
+    last_modified: Option<String>,
+    #[cfg(feature = "http")]
+    etag: Option<String>,
 }
 
 
@@ -189,12 +308,38 @@ impl<'a> RobotFileParser<'a> {
             // the default entry is considered last
             let default_entry = &mut self.default_entry;
             if default_entry.is_empty() {
-                // the first default entry wins
                 *default_entry = entry;
+            } else {
+                // a repeated "User-agent: *" block is merged into the
+                // existing default entry rather than replacing it
+                default_entry.merge(entry);
             }
+        } else if let Some(existing) = self.entries.iter_mut()
+            .find(|existing| existing.useragents == entry.useragents)
+        {
+            // real robots.txt files often split one agent's rules across
+            // several blocks; fold them into a single logical entry
+            existing.merge(entry);
         } else {
-            let entries = &mut self.entries;
-            entries.push(entry);
+            self.entries.push(entry);
+        }
+    }
+
+    /// The entry whose declared user-agent is the most specific match for
+    /// `useragent`, falling back to the `*` default entry when nothing more
+    /// specific applies.
+    fn best_entry(&self, useragent: &str) -> Option<&Entry<'a>> {
+        let ua = useragent.split('/').next().unwrap_or("").to_lowercase();
+        self.entries.iter()
+            .filter_map(|entry| entry.matched_agent_len(&ua).map(|len| (len, entry)))
+            .max_by_key(|(len, _)| *len)
+            .map(|(_, entry)| entry)
+            .or_else(|| if self.default_entry.is_empty() { None } else { Some(&self.default_entry) })
+    }
+
+    fn add_sitemap(&mut self, url: &str) {
+        if let Ok(url) = Url::parse(url) {
+            self.sitemaps.push(url);
         }
     }
 
@@ -210,6 +355,15 @@ impl<'a> RobotFileParser<'a> {
             default_entry: Entry::new(),
             disallow_all: false,
             allow_all: false,
+            sitemaps: vec![],
+            #[cfg(feature = "http")]
+            url: None,
+            #[cfg(feature = "http")]
+            last_checked: None,
+            #[cfg(feature = "http")]
+            last_modified: None,
+            #[cfg(feature = "http")]
+            etag: None,
         };
 
         use url::percent_encoding::percent_decode;
@@ -284,10 +438,10 @@ impl<'a> RobotFileParser<'a> {
                         }
                     }
                     ref x if x == "sitemap" => {
-                        if state != 0 {
-                            entry.add_sitemap(&part1);
-                            state = 2;
-                        }
+                        // a Sitemap directive is file-global: it applies
+                        // regardless of which (or whether any) User-agent
+                        // block it is declared under
+                        this.add_sitemap(&part1);
                     }
                     ref x if x == "request-rate" => {
                         if state != 0 {
@@ -314,6 +468,69 @@ impl<'a> RobotFileParser<'a> {
     }
 
     /// Using the parsed robots.txt decide if useragent can fetch url
+    ///
+    /// `Allow`/`Disallow` paths may use `*` to match any run of characters,
+    /// and a trailing `$` to anchor the match to the end of the url:
+    ///
+    /// ```rust
+    /// use robotstxt::RobotFileParser;
+    ///
+    /// let parser = RobotFileParser::parse("
+    ///     User-agent: *\n\
+    ///     Disallow: /*.php$\n\
+    ///     Allow: /*?*utm_source=\n\
+    /// ");
+    /// assert!(!parser.can_fetch("crawler1", "/index.php"));
+    /// assert!(parser.can_fetch("crawler1", "/index.php?id=1"));
+    /// assert!(parser.can_fetch("crawler1", "/page?id=1&utm_source=test"));
+    /// ```
+    ///
+    /// When several rules match the same url, the one with the longest
+    /// (most specific) path wins, regardless of declaration order; a tie is
+    /// resolved in favor of `Allow`:
+    ///
+    /// ```rust
+    /// use robotstxt::RobotFileParser;
+    ///
+    /// let parser = RobotFileParser::parse("
+    ///     User-agent: *\n\
+    ///     Disallow: /folder\n\
+    ///     Allow: /folder/page\n\
+    /// ");
+    /// assert!(!parser.can_fetch("crawler1", "/folder/other"));
+    /// assert!(parser.can_fetch("crawler1", "/folder/page"));
+    /// ```
+    ///
+    /// Among groups that match the requesting user-agent, the one whose
+    /// declared agent is the longest (most specific) wins:
+    ///
+    /// ```rust
+    /// use robotstxt::RobotFileParser;
+    ///
+    /// let parser = RobotFileParser::parse("
+    ///     User-agent: googlebot\n\
+    ///     Disallow: /no-bots\n\
+    ///     \n\
+    ///     User-agent: googlebot-news\n\
+    ///     Allow: /no-bots\n\
+    /// ");
+    /// assert!(!parser.can_fetch("Googlebot/2.1", "/no-bots"));
+    /// assert!(parser.can_fetch("Googlebot-News", "/no-bots"));
+    /// ```
+    ///
+    /// A `*` immediately before the trailing `$` makes the anchor a no-op,
+    /// since "anything, then end" matches anything after the literal part:
+    ///
+    /// ```rust
+    /// use robotstxt::RobotFileParser;
+    ///
+    /// let parser = RobotFileParser::parse("
+    ///     User-agent: *\n\
+    ///     Disallow: /foo*$\n\
+    /// ");
+    /// assert!(!parser.can_fetch("crawler1", "/foo"));
+    /// assert!(!parser.can_fetch("crawler1", "/foobar"));
+    /// ```
     pub fn can_fetch<T: AsRef<str>>(&self, useragent: T, url: T) -> bool {
         use url::percent_encoding::percent_decode;
 
@@ -326,61 +543,156 @@ impl<'a> RobotFileParser<'a> {
         if self.allow_all {
             return true;
         }
-        // search for given user agent matches
-        // the first match counts
         let decoded_url = String::from_utf8(percent_decode(url.trim().as_bytes()).collect()).unwrap_or("".to_owned());
         let url_str = match decoded_url {
             ref u if !u.is_empty() => u.to_owned(),
             _ => "/".to_owned(),
         };
-        let entries = &self.entries;
-        for entry in &*entries {
-            if entry.applies_to(useragent) {
-                return entry.allowance(&url_str);
-            }
+        match self.best_entry(useragent) {
+            Some(entry) => entry.allowance(&url_str),
+            // agent not found ==> access granted
+            None => true,
         }
-        // try the default entry last
-        let default_entry = &self.default_entry;
-        if !default_entry.is_empty() {
-            return default_entry.allowance(&url_str);
-        }
-        // agent not found ==> access granted
-        true
     }
 
     /// Returns the crawl delay for this user agent as a `Duration`, or None if no crawl delay is defined.
+    ///
+    /// Falls back to the `*` default group's crawl delay if the matched
+    /// group doesn't declare one of its own.
     pub fn crawl_delay<T: AsRef<str>>(&self, useragent: T) -> Option<Duration> {
         let useragent = useragent.as_ref();
-        let entries = &self.entries;
-        for entry in &*entries {
-            if entry.applies_to(useragent) {
-                return entry.crawl_delay();
-            }
-        }
-        None
+        self.best_entry(useragent).and_then(|entry| entry.crawl_delay())
+            .or_else(|| self.default_entry.crawl_delay())
     }
 
-    /// Returns the sitemaps for this user agent as a `Vec<Url>`.
-    pub fn sitemaps<T: AsRef<str>>(&self, useragent: T) -> Option<&Vec<Url>> {
-        let useragent = useragent.as_ref();
-        let entries = &self.entries;
-        for entry in &*entries {
-            if entry.applies_to(useragent) {
-                return Some(entry.sitemaps());
-            }
-        }
-        None
+    /// Returns every `Sitemap:` declared in this file, regardless of which
+    /// (if any) `User-agent` group it appeared under.
+    ///
+    /// `Sitemap:` is a file-global directive, not a per-group one, so there
+    /// is no per-useragent equivalent of this method.
+    ///
+    /// ```rust
+    /// use robotstxt::RobotFileParser;
+    ///
+    /// let parser = RobotFileParser::parse("
+    ///     Sitemap: https://example.com/sitemap.xml\n\
+    ///     User-agent: *\n\
+    ///     Disallow: /private\n\
+    /// ");
+    /// assert_eq!(parser.all_sitemaps().len(), 1);
+    /// ```
+    pub fn all_sitemaps(&self) -> &Vec<Url> {
+        &self.sitemaps
     }
 
     /// Returns the request rate for this user agent as a `RequestRate`, or None if not request rate is defined
+    ///
+    /// Falls back to the `*` default group's request rate if the matched
+    /// group doesn't declare one of its own.
     pub fn request_rate<T: AsRef<str>>(&self, useragent: T) -> Option<RequestRate> {
         let useragent = useragent.as_ref();
-        let entries = &self.entries;
-        for entry in &*entries {
-            if entry.applies_to(useragent) {
-                return entry.request_rate();
-            }
+        self.best_entry(useragent).and_then(|entry| entry.request_rate())
+            .or_else(|| self.default_entry.request_rate())
+    }
+}
+
+#[cfg(feature = "http")]
+impl<'a> RobotFileParser<'a> {
+    /// Create a parser bound to `url`, without fetching it yet.
+    ///
+    /// Call [`fetch`](RobotFileParser::fetch) (or [`read`](RobotFileParser::read))
+    /// to actually download and parse the robots.txt file.
+    pub fn new<T: AsRef<str>>(url: T) -> Result<Self, url::ParseError> {
+        let url = Url::parse(url.as_ref())?;
+        Ok(RobotFileParser {
+            entries: vec![],
+            default_entry: Entry::new(),
+            disallow_all: false,
+            allow_all: false,
+            sitemaps: vec![],
+            url: Some(url),
+            last_checked: None,
+            last_modified: None,
+            etag: None,
+        })
+    }
+
+    /// Change the url this parser fetches from.
+    pub fn set_url<T: AsRef<str>>(&mut self, url: T) -> Result<(), url::ParseError> {
+        self.url = Some(Url::parse(url.as_ref())?);
+        Ok(())
+    }
+
+    /// Returns the time this parser last fetched its robots.txt, if ever.
+    pub fn mtime(&self) -> Option<SystemTime> {
+        self.last_checked
+    }
+
+    /// Fetch the url set via [`new`](RobotFileParser::new) or
+    /// [`set_url`](RobotFileParser::set_url) and (re-)parse its rules.
+    ///
+    /// A `4xx` response (other than `429`) is treated as "no robots.txt
+    /// present", so everything is allowed. A `429` or `5xx` response is
+    /// treated as "server unavailable", so everything is disallowed until a
+    /// later fetch succeeds. On success, the `Last-Modified`/`ETag` response
+    /// headers are kept so the next call to `fetch` issues a conditional
+    /// request and skips reparsing on `304 Not Modified`.
+    pub fn fetch(&mut self) -> Result<(), reqwest::Error> {
+        let url = match &self.url {
+            Some(url) => url.as_str(),
+            None => return Ok(()),
+        };
+
+        let client = reqwest::blocking::Client::new();
+        // pass the url as a string: this crate is pinned to `url` 1.x for
+        // its percent_encoding re-export, while reqwest depends on `url`
+        // 2.x, so a `url::Url` value can't cross this boundary directly
+        let mut request = client.get(url);
+        if let Some(etag) = &self.etag {
+            request = request.header("If-None-Match", etag.as_str());
+        }
+        if let Some(last_modified) = &self.last_modified {
+            request = request.header("If-Modified-Since", last_modified.as_str());
+        }
+
+        let response = request.send()?;
+        let status = response.status();
+
+        if status.as_u16() == 304 {
+            // not modified, keep the rules we already parsed
+        } else if status.as_u16() == 429 {
+            self.disallow_all = true;
+            self.allow_all = false;
+        } else if status.is_client_error() {
+            self.allow_all = true;
+            self.disallow_all = false;
+        } else if status.is_server_error() {
+            self.disallow_all = true;
+            self.allow_all = false;
+        } else if status.is_success() {
+            self.etag = response.headers().get("etag")
+                .and_then(|v| v.to_str().ok())
+                .map(|s| s.to_owned());
+            self.last_modified = response.headers().get("last-modified")
+                .and_then(|v| v.to_str().ok())
+                .map(|s| s.to_owned());
+
+            let body = response.text()?;
+            let parsed = RobotFileParser::parse(body);
+            self.entries = parsed.entries;
+            self.default_entry = parsed.default_entry;
+            self.sitemaps = parsed.sitemaps;
+            self.disallow_all = false;
+            self.allow_all = false;
         }
-        None
+
+        self.last_checked = Some(SystemTime::now());
+        Ok(())
+    }
+
+    /// Alias for [`fetch`](RobotFileParser::fetch), matching the ancestor
+    /// `robotparser` crate's `read` method.
+    pub fn read(&mut self) -> Result<(), reqwest::Error> {
+        self.fetch()
     }
 }